@@ -1,11 +1,15 @@
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use clap::Parser;
 use mysql_async::prelude::*;
-use mysql_async::{Conn, Pool};
+use mysql_async::{Conn, Pool, TxOpts};
+use rand::Rng;
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashSet};
 use std::fs::File;
-use std::io::{BufRead, BufReader};
-use std::path::PathBuf;
+use std::io::{BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 /// HDFS log entry structure
 #[derive(Debug, Serialize, Deserialize)]
@@ -16,6 +20,23 @@ struct LogEntry {
     tenant_id: i32,
 }
 
+/// Input file format for the HDFS log asset
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum LogFormat {
+    Json,
+    Parquet,
+    Csv,
+}
+
+/// The asset file name this tool looks for under `--asset-dir`, per format
+fn asset_file_name(format: LogFormat) -> &'static str {
+    match format {
+        LogFormat::Json => "hdfs-logs-multitenants.json",
+        LogFormat::Parquet => "hdfs-logs-multitenants.parquet",
+        LogFormat::Csv => "hdfs-logs-multitenants.csv",
+    }
+}
+
 /// Command-line arguments
 #[derive(Parser, Debug)]
 #[command(name = "hdfs-log-reader")]
@@ -40,42 +61,148 @@ struct Args {
     #[arg(long, default_value_t = 4000)]
     tidb_port: u16,
 
-    /// Directory containing the asset files (when empty, uses current directory)
+    /// Database user
+    #[arg(long, env = "HDFS_LOG_READER_USER", default_value = "root")]
+    user: String,
+
+    /// Database password
+    #[arg(long, env = "HDFS_LOG_READER_PASSWORD", default_value = "")]
+    password: String,
+
+    /// Database name to connect to
+    #[arg(long, default_value = "test")]
+    database: String,
+
+    /// Maximum number of pooled connections
+    #[arg(long, default_value_t = 10)]
+    pool_size: usize,
+
+    /// Connect to TiDB over TLS
+    #[arg(long, default_value_t = false)]
+    ssl: bool,
+
+    /// Maximum attempts for a batch insert before giving up, with exponential backoff
+    /// between retries
+    #[arg(long, default_value_t = 5)]
+    max_retries: u32,
+
+    /// Directory containing the asset files (when empty, uses current directory).
+    /// When `webhdfs_url` is set, this is interpreted as the directory on HDFS instead.
     #[arg(long, default_value = "")]
     asset_dir: String,
+
+    /// WebHDFS NameNode endpoint, e.g. "http://namenode:9870". When set, logs are
+    /// streamed over the WebHDFS REST API instead of read from the local filesystem.
+    #[arg(long)]
+    webhdfs_url: Option<String>,
+
+    /// HDFS user to send as the `user.name` pseudo-auth query parameter
+    #[arg(long)]
+    webhdfs_user: Option<String>,
+
+    /// List `asset_dir` on HDFS (via `op=LISTSTATUS`) and concatenate every file in it,
+    /// instead of opening `hdfs-logs-multitenants.json` directly
+    #[arg(long, default_value_t = false)]
+    webhdfs_list_dir: bool,
+
+    /// Number of concurrent insert workers
+    #[arg(long, default_value_t = default_worker_count())]
+    workers: usize,
+
+    /// Capacity of the bounded channel between the parser thread and the insert workers
+    #[arg(long, default_value_t = 10_000)]
+    channel_cap: usize,
+
+    /// Input file format of the log asset
+    #[arg(long, value_enum, default_value_t = LogFormat::Json)]
+    format: LogFormat,
+
+    /// Drop and recreate the table instead of applying migrations (destroys prior loads)
+    #[arg(long, default_value_t = false)]
+    recreate: bool,
+
+    /// Apply pending migrations and exit without processing any logs
+    #[arg(long, default_value_t = false)]
+    migrate_only: bool,
+
+    /// Resume an interrupted run, skipping rows already recorded as inserted in the job log
+    #[arg(long, default_value_t = false)]
+    resume: bool,
+
+    /// Job identity used to look up progress in the job log (defaults to the input file path)
+    #[arg(long)]
+    job_id: Option<String>,
+
+    /// Path to the SQLite job log sidecar used by `--resume`
+    #[arg(long, default_value = "hdfs-log-reader-jobs.sqlite3")]
+    job_log_path: String,
+}
+
+/// Default `--workers` to the number of available CPUs
+fn default_worker_count() -> usize {
+    std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
 }
 
-/// Connect to the database
-async fn connect_to_database(tidb_host: &str, tidb_port: u16) -> Result<Conn> {
-    println!("Connecting to tidb, host={} port={}", tidb_host, tidb_port);
+/// Connect to the database, returning a `Pool` that workers can draw connections from
+async fn connect_to_database(args: &Args) -> Result<Pool> {
+    println!(
+        "Connecting to tidb, host={} port={} user={} db={} pool_size={} ssl={}",
+        args.tidb_host, args.tidb_port, args.user, args.database, args.pool_size, args.ssl
+    );
+
+    let pool_constraints = mysql_async::PoolConstraints::new(1, args.pool_size)
+        .context("--pool-size must be at least 1")?;
+
+    let mut opts = mysql_async::OptsBuilder::default()
+        .ip_or_hostname(&args.tidb_host)
+        .tcp_port(args.tidb_port)
+        .user(Some(&args.user))
+        .pass(Some(&args.password))
+        .db_name(Some(&args.database))
+        .pool_opts(mysql_async::PoolOpts::default().with_constraints(pool_constraints));
 
-    let opts = mysql_async::OptsBuilder::default()
-        .ip_or_hostname(tidb_host)
-        .tcp_port(tidb_port)
-        .user(Some("root"))
-        .pass(Some(""))
-        .db_name(Some("test"));
+    if args.ssl {
+        opts = opts.ssl_opts(Some(mysql_async::SslOpts::default()));
+    }
 
     let pool = Pool::new(opts);
-    let conn = pool
-        .get_conn()
+    // Eagerly check connectivity so connection errors surface before we start work.
+    pool.get_conn()
         .await
         .context("Error connecting to the database")?;
 
     println!("Successfully connected to the database");
-    Ok(conn)
+    Ok(pool)
 }
 
-/// Create the HDFS log table
-async fn create_hdfs_log_table(conn: &mut Conn, table_name: &str) -> Result<()> {
-    // Drop table if exists
+/// Drop and recreate the HDFS log table, destroying any prior loads. Opt in via `--recreate`;
+/// prefer [`run_migrations`] for incremental ingestion.
+async fn recreate_hdfs_log_table(conn: &mut Conn, table_name: &str) -> Result<()> {
     conn.exec_drop(format!("DROP TABLE IF EXISTS {}", table_name), ())
         .await
         .context("Error dropping table")?;
     println!("Table {} dropped successfully", table_name);
 
-    // Create table
-    let create_table_sql = format!(
+    conn.exec_drop(create_table_sql(table_name), ())
+        .await
+        .context("Error creating table")?;
+    println!("Table {} created successfully", table_name);
+
+    Ok(())
+}
+
+/// One embedded, versioned schema change. `sql` is templated with the target table name,
+/// since the table name is a runtime CLI argument rather than something fixed at compile time.
+struct Migration {
+    version: u32,
+    name: &'static str,
+    sql: fn(&str) -> String,
+}
+
+fn create_table_sql(table_name: &str) -> String {
+    format!(
         r#"
         CREATE TABLE IF NOT EXISTS {} (
             id BIGINT AUTO_INCREMENT,
@@ -87,28 +214,387 @@ async fn create_hdfs_log_table(conn: &mut Conn, table_name: &str) -> Result<()>
         ) AUTO_INCREMENT = 1000
         "#,
         table_name
-    );
+    )
+}
+
+/// The ordered set of migrations, oldest first. Append new ones here; never edit an
+/// already-released migration, since its version may already be recorded as applied.
+fn migrations() -> Vec<Migration> {
+    vec![Migration {
+        version: 1,
+        name: "create_table",
+        sql: create_table_sql,
+    }]
+}
 
-    conn.exec_drop(create_table_sql, ())
+/// Create the `_hdfs_log_migrations` bookkeeping table if it doesn't exist yet
+async fn ensure_migrations_table(conn: &mut Conn) -> Result<()> {
+    conn.exec_drop(
+        r#"
+        CREATE TABLE IF NOT EXISTS _hdfs_log_migrations (
+            table_name VARCHAR(255) NOT NULL,
+            version INT NOT NULL,
+            name VARCHAR(255) NOT NULL,
+            applied_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+            PRIMARY KEY (table_name, version)
+        )
+        "#,
+        (),
+    )
+    .await
+    .context("Error creating migrations bookkeeping table")?;
+
+    Ok(())
+}
+
+/// Versions of migrations already applied to `table_name`
+async fn applied_migration_versions(conn: &mut Conn, table_name: &str) -> Result<HashSet<u32>> {
+    let versions: Vec<u32> = conn
+        .exec(
+            "SELECT version FROM _hdfs_log_migrations WHERE table_name = ?",
+            (table_name,),
+        )
         .await
-        .context("Error creating table")?;
-    println!("Table {} created successfully", table_name);
+        .context("Error reading applied migrations")?;
+
+    Ok(versions.into_iter().collect())
+}
+
+/// Apply every pending migration for `table_name`, each inside its own transaction, so
+/// repeated or incremental ingestion never loses data the way `--recreate` does.
+async fn run_migrations(conn: &mut Conn, table_name: &str) -> Result<()> {
+    ensure_migrations_table(conn).await?;
+    let applied = applied_migration_versions(conn, table_name).await?;
+
+    for migration in migrations() {
+        if applied.contains(&migration.version) {
+            continue;
+        }
+
+        println!(
+            "Applying migration {} ({}) to {}",
+            migration.version, migration.name, table_name
+        );
+
+        let mut tx = conn
+            .start_transaction(TxOpts::default())
+            .await
+            .context("Error starting migration transaction")?;
+
+        tx.exec_drop((migration.sql)(table_name), ())
+            .await
+            .with_context(|| {
+                format!(
+                    "Error applying migration {} ({})",
+                    migration.version, migration.name
+                )
+            })?;
+
+        tx.exec_drop(
+            "INSERT INTO _hdfs_log_migrations (table_name, version, name) VALUES (?, ?, ?)",
+            (table_name, migration.version, migration.name),
+        )
+        .await
+        .context("Error recording applied migration")?;
+
+        tx.commit().await.context("Error committing migration")?;
+    }
+
+    println!("Table {} is up to date", table_name);
 
     Ok(())
 }
 
-/// Read HDFS logs from a JSON file
-fn read_hdfs_logs(
-    file_path: &str,
-    max_rows: Option<usize>,
-) -> Result<impl Iterator<Item = Result<LogEntry>>> {
-    let file = File::open(file_path)
-        .with_context(|| format!("Error opening file {}", file_path))?;
-    let reader = BufReader::new(file);
+/// A local SQLite sidecar tracking ingestion progress, so an interrupted run can resume
+/// instead of starting over. Shared across insert workers behind a `Mutex`, since rusqlite
+/// connections aren't usable concurrently.
+struct JobLog {
+    conn: rusqlite::Connection,
+}
+
+impl JobLog {
+    fn open(path: &Path) -> Result<Self> {
+        let conn = rusqlite::Connection::open(path)
+            .with_context(|| format!("Error opening job log {}", path.display()))?;
+
+        conn.pragma_update(None, "journal_mode", "WAL")
+            .context("Error enabling WAL mode on the job log")?;
+
+        conn.execute(
+            r#"
+            CREATE TABLE IF NOT EXISTS job_log (
+                job_id TEXT PRIMARY KEY,
+                input_file TEXT NOT NULL,
+                committed_offset INTEGER NOT NULL DEFAULT 0,
+                batches_committed INTEGER NOT NULL DEFAULT 0,
+                started_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+                updated_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now'))
+            )
+            "#,
+            (),
+        )
+        .context("Error creating job_log table")?;
+
+        Ok(Self { conn })
+    }
+
+    /// Register `job_id` on first sight, then return how many rows are already committed
+    /// for it (0 for a fresh job)
+    fn start_or_resume(&self, job_id: &str, input_file: &str) -> Result<u64> {
+        self.conn
+            .execute(
+                "INSERT OR IGNORE INTO job_log (job_id, input_file) VALUES (?1, ?2)",
+                rusqlite::params![job_id, input_file],
+            )
+            .context("Error registering job in the job log")?;
+
+        let committed_offset: i64 = self
+            .conn
+            .query_row(
+                "SELECT committed_offset FROM job_log WHERE job_id = ?1",
+                rusqlite::params![job_id],
+                |row| row.get(0),
+            )
+            .context("Error reading committed offset from the job log")?;
+
+        Ok(committed_offset as u64)
+    }
+
+    /// Forget any prior progress recorded for `job_id`, so a subsequent `start_or_resume`
+    /// treats it as a fresh job. Used when `--recreate` wipes the data table out from under
+    /// a job id that a previous run already made progress on, since otherwise a resumed run
+    /// would skip rows that no longer exist in the (just-recreated, empty) table.
+    fn reset_job(&self, job_id: &str) -> Result<()> {
+        self.conn
+            .execute(
+                "DELETE FROM job_log WHERE job_id = ?1",
+                rusqlite::params![job_id],
+            )
+            .context("Error resetting job log entry")?;
+
+        Ok(())
+    }
+
+    /// Persist an advanced watermark for `job_id`. `committed_offset` is the absolute count
+    /// of source rows now known to form an unbroken prefix of durably-inserted rows, as
+    /// recomputed by [`RowWatermark`] - never a per-batch delta, since concurrent workers
+    /// finish batches out of source order and a delta would double-count or skip rows.
+    fn advance_committed_offset(&self, job_id: &str, committed_offset: u64) -> Result<()> {
+        self.conn
+            .execute(
+                r#"
+                UPDATE job_log
+                SET committed_offset = ?1,
+                    batches_committed = batches_committed + 1,
+                    updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now')
+                WHERE job_id = ?2
+                "#,
+                rusqlite::params![committed_offset as i64, job_id],
+            )
+            .context("Error updating the job log")?;
+
+        Ok(())
+    }
+}
+
+/// Tracks which source rows (identified by their absolute index in the input) have been
+/// durably inserted, so the on-disk `committed_offset` only ever advances over a
+/// contiguous prefix. Concurrent workers finish batches in whatever order the database
+/// finishes them in, not source order, so a batch covering later rows can complete before
+/// one covering earlier rows; its row indices sit in `pending` until the gap closes.
+struct RowWatermark {
+    /// One past the highest row index known to form an unbroken prefix from the start
+    /// (or from `resume_offset`, on a resumed job) of durably-inserted rows.
+    next: u64,
+    /// Row indices from completed batches that haven't yet been folded into `next`
+    /// because an earlier row is still outstanding.
+    pending: BTreeSet<u64>,
+}
 
+/// A job log shared between the concurrent insert workers, alongside the job id they
+/// report progress under
+#[derive(Clone)]
+struct JobLogHandle {
+    job_log: Arc<Mutex<JobLog>>,
+    job_id: String,
+    watermark: Arc<Mutex<RowWatermark>>,
+}
+
+/// A WebHDFS `LISTSTATUS` response, trimmed to the fields we need
+#[derive(Debug, Deserialize)]
+struct WebHdfsListStatus {
+    #[serde(rename = "FileStatuses")]
+    file_statuses: WebHdfsFileStatuses,
+}
+
+#[derive(Debug, Deserialize)]
+struct WebHdfsFileStatuses {
+    #[serde(rename = "FileStatus")]
+    file_status: Vec<WebHdfsFileStatus>,
+}
+
+#[derive(Debug, Deserialize)]
+struct WebHdfsFileStatus {
+    #[serde(rename = "pathSuffix")]
+    path_suffix: String,
+    #[serde(rename = "type")]
+    kind: String,
+}
+
+/// Append the `user.name` pseudo-auth parameter to a WebHDFS URL, if configured
+fn with_webhdfs_user(url: String, user_name: Option<&str>) -> String {
+    match user_name {
+        Some(user) => format!("{}&user.name={}", url, user),
+        None => url,
+    }
+}
+
+/// Open a single file on HDFS via WebHDFS's `op=OPEN`, following the 307 redirect the
+/// NameNode returns to the DataNode that actually holds the data
+fn open_webhdfs_file(
+    client: &reqwest::blocking::Client,
+    namenode_url: &str,
+    hdfs_path: &str,
+    user_name: Option<&str>,
+) -> Result<Box<dyn BufRead + Send>> {
+    let open_url = with_webhdfs_user(
+        format!("{}/webhdfs/v1{}?op=OPEN", namenode_url, hdfs_path),
+        user_name,
+    );
+
+    let redirect = client
+        .get(&open_url)
+        .send()
+        .with_context(|| format!("Error opening WebHDFS path {}", hdfs_path))?;
+
+    let data_node_url = if redirect.status().is_redirection() {
+        redirect
+            .headers()
+            .get(reqwest::header::LOCATION)
+            .context("WebHDFS redirect response is missing a Location header")?
+            .to_str()
+            .context("WebHDFS Location header is not valid UTF-8")?
+            .to_string()
+    } else {
+        open_url
+    };
+
+    let response = client
+        .get(&data_node_url)
+        .send()
+        .with_context(|| format!("Error streaming WebHDFS path {}", hdfs_path))?
+        .error_for_status()
+        .with_context(|| format!("WebHDFS returned an error for path {}", hdfs_path))?;
+
+    Ok(Box::new(BufReader::new(response)))
+}
+
+/// List the files under `hdfs_dir` via `op=LISTSTATUS`
+fn list_webhdfs_dir(
+    client: &reqwest::blocking::Client,
+    namenode_url: &str,
+    hdfs_dir: &str,
+    user_name: Option<&str>,
+) -> Result<Vec<String>> {
+    let list_url = with_webhdfs_user(
+        format!("{}/webhdfs/v1{}?op=LISTSTATUS", namenode_url, hdfs_dir),
+        user_name,
+    );
+
+    let status: WebHdfsListStatus = client
+        .get(&list_url)
+        .send()
+        .with_context(|| format!("Error listing WebHDFS directory {}", hdfs_dir))?
+        .error_for_status()
+        .with_context(|| format!("WebHDFS returned an error listing {}", hdfs_dir))?
+        .json()
+        .context("Error parsing WebHDFS LISTSTATUS response")?;
+
+    let paths = status
+        .file_statuses
+        .file_status
+        .into_iter()
+        .filter(|entry| entry.kind == "FILE")
+        .map(|entry| format!("{}/{}", hdfs_dir.trim_end_matches('/'), entry.path_suffix))
+        .collect();
+
+    Ok(paths)
+}
+
+/// Open every log file under `hdfs_dir` on HDFS and chain them into a single reader
+fn open_webhdfs_dir(
+    namenode_url: &str,
+    hdfs_dir: &str,
+    user_name: Option<&str>,
+) -> Result<Box<dyn BufRead + Send>> {
+    let client = reqwest::blocking::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .context("Error building WebHDFS HTTP client")?;
+
+    let paths = list_webhdfs_dir(&client, namenode_url, hdfs_dir, user_name)?;
+    if paths.is_empty() {
+        bail!("No files found under WebHDFS directory {}", hdfs_dir);
+    }
+
+    let mut readers: Vec<Box<dyn BufRead + Send>> = Vec::with_capacity(paths.len());
+    for path in paths {
+        readers.push(open_webhdfs_file(&client, namenode_url, &path, user_name)?);
+    }
+
+    let mut chained = readers.into_iter();
+    let first = chained.next().expect("paths is non-empty");
+    let reader = chained.fold(first, |acc, next| {
+        Box::new(BufReader::new(acc.chain(next)))
+    });
+
+    Ok(reader)
+}
+
+/// Open the configured log source, local or WebHDFS, as a single buffered reader
+fn open_log_source(args: &Args, infilename: &PathBuf) -> Result<Box<dyn BufRead + Send>> {
+    match &args.webhdfs_url {
+        Some(namenode_url) => {
+            let hdfs_dir = if args.asset_dir.is_empty() {
+                "/"
+            } else {
+                args.asset_dir.as_str()
+            };
+
+            if args.webhdfs_list_dir {
+                open_webhdfs_dir(namenode_url, hdfs_dir, args.webhdfs_user.as_deref())
+            } else {
+                let client = reqwest::blocking::Client::builder()
+                    .redirect(reqwest::redirect::Policy::none())
+                    .build()
+                    .context("Error building WebHDFS HTTP client")?;
+                let hdfs_path = format!(
+                    "{}/{}",
+                    hdfs_dir.trim_end_matches('/'),
+                    asset_file_name(args.format)
+                );
+                open_webhdfs_file(&client, namenode_url, &hdfs_path, args.webhdfs_user.as_deref())
+            }
+        }
+        None => {
+            let file = File::open(infilename).with_context(|| {
+                format!("Error opening file {}", infilename.display())
+            })?;
+            Ok(Box::new(BufReader::new(file)))
+        }
+    }
+}
+
+/// Read newline-delimited JSON logs from a buffered reader
+fn read_json_logs(
+    reader: Box<dyn BufRead + Send>,
+    resume_offset: usize,
+    max_rows: Option<usize>,
+) -> Result<impl Iterator<Item = Result<LogEntry>> + Send> {
     let iter = reader
         .lines()
         .enumerate()
+        .skip(resume_offset)
         .take(max_rows.unwrap_or(usize::MAX))
         .map(|(i, line)| {
             let line = line.with_context(|| format!("Error reading line {}", i + 1))?;
@@ -119,38 +605,333 @@ fn read_hdfs_logs(
     Ok(iter)
 }
 
-/// Insert a batch of HDFS logs into the database
+/// Read CSV logs from a buffered reader, mapping the header row onto `LogEntry` fields
+fn read_csv_logs(
+    reader: Box<dyn BufRead + Send>,
+    resume_offset: usize,
+    max_rows: Option<usize>,
+) -> Result<impl Iterator<Item = Result<LogEntry>> + Send> {
+    let csv_reader = csv::ReaderBuilder::new().has_headers(true).from_reader(reader);
+
+    let iter = csv_reader
+        .into_deserialize::<LogEntry>()
+        .enumerate()
+        .skip(resume_offset)
+        .take(max_rows.unwrap_or(usize::MAX))
+        .map(|(i, record)| record.with_context(|| format!("Error parsing CSV at row {}", i + 1)));
+
+    Ok(iter)
+}
+
+/// Read Parquet logs, projecting the `timestamp`, `severity_text`, `body` and `tenant_id`
+/// columns out of each row group. Parquet needs random-access reads, so unlike the JSON
+/// and CSV formats this always opens a local file rather than the generic `BufRead` source.
+fn read_parquet_logs(
+    file_path: &str,
+    resume_offset: usize,
+    max_rows: Option<usize>,
+) -> Result<Box<dyn Iterator<Item = Result<LogEntry>> + Send>> {
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use parquet::arrow::ProjectionMask;
+
+    let file =
+        File::open(file_path).with_context(|| format!("Error opening file {}", file_path))?;
+
+    let builder = ParquetRecordBatchReaderBuilder::try_new(file)
+        .context("Error reading Parquet metadata")?;
+
+    let column_names = ["timestamp", "severity_text", "body", "tenant_id"];
+    let projected: Vec<usize> = column_names
+        .iter()
+        .map(|name| {
+            builder
+                .schema()
+                .index_of(name)
+                .with_context(|| format!("Parquet file is missing column '{}'", name))
+        })
+        .collect::<Result<_>>()?;
+    let mask = ProjectionMask::roots(builder.parquet_schema(), projected);
+
+    let batch_reader = builder
+        .with_projection(mask)
+        .build()
+        .context("Error building Parquet reader")?;
+
+    let rows = batch_reader
+        .flat_map(|batch_result| match batch_result {
+            Ok(batch) => parquet_batch_to_logs(&batch),
+            Err(e) => vec![Err(anyhow::Error::new(e).context("Error reading Parquet batch"))],
+        })
+        .skip(resume_offset)
+        .take(max_rows.unwrap_or(usize::MAX));
+
+    Ok(Box::new(rows))
+}
+
+/// Convert one Arrow `RecordBatch` of projected columns into `LogEntry` rows
+fn parquet_batch_to_logs(batch: &arrow::record_batch::RecordBatch) -> Vec<Result<LogEntry>> {
+    use arrow::array::{Array, Int32Array, Int64Array, StringArray};
+
+    let column = |name: &str| -> Result<&arrow::array::ArrayRef> {
+        batch
+            .column_by_name(name)
+            .with_context(|| format!("Parquet batch is missing column '{}'", name))
+    };
+
+    let entries = (|| -> Result<Vec<Result<LogEntry>>> {
+        let timestamp = column("timestamp")?
+            .as_any()
+            .downcast_ref::<Int64Array>()
+            .context("Column 'timestamp' is not an Int64 array")?;
+        let severity_text = column("severity_text")?
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .context("Column 'severity_text' is not a String array")?;
+        let body = column("body")?
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .context("Column 'body' is not a String array")?;
+        let tenant_id = column("tenant_id")?
+            .as_any()
+            .downcast_ref::<Int32Array>()
+            .context("Column 'tenant_id' is not an Int32 array")?;
+
+        Ok((0..batch.num_rows())
+            .map(|i| {
+                Ok(LogEntry {
+                    timestamp: timestamp.value(i),
+                    severity_text: severity_text.value(i).to_string(),
+                    body: body.value(i).to_string(),
+                    tenant_id: tenant_id.value(i),
+                })
+            })
+            .collect())
+    })();
+
+    match entries {
+        Ok(rows) => rows,
+        Err(e) => vec![Err(e)],
+    }
+}
+
+/// Build the log iterator for the configured format and source (local file or WebHDFS).
+/// `resume_offset` rows are skipped on the raw source *before* `--max-rows` is applied, so
+/// resuming a job and capping its row count compose correctly instead of the cap truncating
+/// the source ahead of the skip.
+fn build_log_iterator(
+    args: &Args,
+    infilename: &PathBuf,
+    resume_offset: usize,
+) -> Result<Box<dyn Iterator<Item = Result<LogEntry>> + Send>> {
+    if args.format == LogFormat::Parquet {
+        if args.webhdfs_url.is_some() {
+            bail!("`--format parquet` requires a local file; WebHDFS streaming is not supported for Parquet");
+        }
+        let file_path = infilename.to_str().context("Invalid file path")?;
+        return read_parquet_logs(file_path, resume_offset, args.max_rows);
+    }
+
+    let reader = open_log_source(args, infilename)?;
+    match args.format {
+        LogFormat::Json => Ok(Box::new(read_json_logs(reader, resume_offset, args.max_rows)?)),
+        LogFormat::Csv => Ok(Box::new(read_csv_logs(reader, resume_offset, args.max_rows)?)),
+        LogFormat::Parquet => unreachable!("handled above"),
+    }
+}
+
+/// Whether a mysql_async error looks like a transient connection problem (reset, timeout,
+/// broken pipe) worth retrying on a fresh connection, as opposed to a genuine data error
+/// (bad SQL, constraint violation) that will just fail again
+fn is_transient_db_error(err: &mysql_async::Error) -> bool {
+    matches!(
+        err,
+        mysql_async::Error::Io(_) | mysql_async::Error::Driver(_)
+    )
+}
+
+/// Exponential backoff with jitter, capped at 10s, for retrying a failed insert
+fn insert_retry_delay(attempt: u32) -> Duration {
+    let base_ms = 200u64.saturating_mul(1u64 << attempt.min(8));
+    let base_ms = base_ms.min(10_000);
+    let jitter_ms = rand::thread_rng().gen_range(0..=base_ms / 2);
+    Duration::from_millis(base_ms + jitter_ms)
+}
+
+/// Insert a batch of HDFS logs into the database, retrying transient failures with
+/// exponential backoff on a freshly pooled connection. Only genuine data errors are
+/// propagated to the caller, since retrying those would never succeed.
 async fn insert_hdfs_logs_batch(
+    pool: &Pool,
     conn: &mut Conn,
     table_name: &str,
     logs: &[LogEntry],
+    max_retries: u32,
 ) -> Result<()> {
     let sql = format!(
         "INSERT INTO {} (timestamp, severity_text, body, tenant_id) VALUES (?, ?, ?, ?)",
         table_name
     );
 
-    let params: Vec<_> = logs
-        .iter()
-        .map(|log| {
-            (
-                log.timestamp,
-                &log.severity_text,
-                &log.body,
-                log.tenant_id,
-            )
-        })
-        .collect();
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
 
-    conn.exec_batch(sql, params)
+        let params: Vec<_> = logs
+            .iter()
+            .map(|log| {
+                (
+                    log.timestamp,
+                    &log.severity_text,
+                    &log.body,
+                    log.tenant_id,
+                )
+            })
+            .collect();
+
+        let outcome: Result<(), mysql_async::Error> = async {
+            let mut tx = conn.start_transaction(TxOpts::default()).await?;
+            tx.exec_batch(sql.clone(), params).await?;
+            tx.commit().await
+        }
+        .await;
+
+        match outcome {
+            Ok(()) => {
+                println!(
+                    "{} logs from batch inserted successfully into {}",
+                    logs.len(),
+                    table_name
+                );
+                return Ok(());
+            }
+            Err(e) if attempt < max_retries && is_transient_db_error(&e) => {
+                let delay = insert_retry_delay(attempt);
+                eprintln!(
+                    "Transient error inserting batch into {} (attempt {}/{}): {}. Retrying in {:?}",
+                    table_name, attempt, max_retries, e, delay
+                );
+                tokio::time::sleep(delay).await;
+
+                *conn = pool
+                    .get_conn()
+                    .await
+                    .context("Error reconnecting after a transient insert failure")?;
+            }
+            Err(e) => {
+                return Err(e).with_context(|| {
+                    format!(
+                        "Error inserting batch into {} after {} attempt(s)",
+                        table_name, attempt
+                    )
+                });
+            }
+        }
+    }
+}
+
+/// Read and parse logs on a dedicated thread, pushing entries onto a bounded channel so
+/// slow inserters apply backpressure instead of letting parsed logs pile up in memory.
+/// Each entry is tagged with its absolute row index in the source (`resume_offset` plus
+/// its position in `log_iter`, which itself already starts past any skipped rows), so
+/// workers can report exactly which rows a batch covers instead of just a count.
+fn spawn_log_producer(
+    log_iter: Box<dyn Iterator<Item = Result<LogEntry>> + Send>,
+    resume_offset: u64,
+    sender: crossbeam_channel::Sender<(u64, LogEntry)>,
+) -> std::thread::JoinHandle<Result<usize>> {
+    std::thread::spawn(move || {
+        let mut total_read = 0;
+        for (i, log_result) in log_iter.enumerate() {
+            match log_result {
+                Ok(log) => {
+                    total_read += 1;
+                    if sender.send((resume_offset + i as u64, log)).is_err() {
+                        // Every worker has shut down; no point reading further.
+                        break;
+                    }
+                }
+                Err(e) => eprintln!("Error processing log entry: {}", e),
+            }
+        }
+        Ok(total_read)
+    })
+}
+
+/// Drain the channel into `batch_size`-sized batches and insert them with a connection
+/// of our own, returning how many logs this worker inserted
+async fn run_insert_worker(
+    worker_id: usize,
+    pool: Pool,
+    table_name: String,
+    batch_size: usize,
+    max_retries: u32,
+    receiver: crossbeam_channel::Receiver<(u64, LogEntry)>,
+    job_log: Option<JobLogHandle>,
+) -> Result<usize> {
+    let mut conn = pool
+        .get_conn()
         .await
-        .context("Error inserting batch")?;
+        .context("Error getting a pooled connection for a worker")?;
 
-    println!(
-        "{} logs from batch inserted successfully into {}",
-        logs.len(),
-        table_name
-    );
+    let mut batch = Vec::with_capacity(batch_size);
+    let mut batch_seqs = Vec::with_capacity(batch_size);
+    let mut total_inserted = 0;
+
+    while let Ok((seq, log)) = receiver.recv() {
+        batch_seqs.push(seq);
+        batch.push(log);
+        if batch.len() >= batch_size {
+            insert_hdfs_logs_batch(&pool, &mut conn, &table_name, &batch, max_retries).await?;
+            total_inserted += batch.len();
+            record_committed_batch(&job_log, &batch_seqs)?;
+            batch.clear();
+            batch_seqs.clear();
+        }
+    }
+
+    if !batch.is_empty() {
+        insert_hdfs_logs_batch(&pool, &mut conn, &table_name, &batch, max_retries).await?;
+        total_inserted += batch.len();
+        record_committed_batch(&job_log, &batch_seqs)?;
+    }
+
+    println!("Worker {} finished, inserted {} logs", worker_id, total_inserted);
+    Ok(total_inserted)
+}
+
+/// Fold a completed batch's row indices into the shared [`RowWatermark`] and, if they
+/// close a contiguous run starting at the current watermark, persist the advanced offset.
+/// A batch that completes ahead of an earlier one just grows `pending` without touching
+/// disk, so a resumed run only ever skips rows that are truly committed.
+fn record_committed_batch(job_log: &Option<JobLogHandle>, seqs: &[u64]) -> Result<()> {
+    let Some(handle) = job_log else {
+        return Ok(());
+    };
+
+    let committed_offset = {
+        let mut watermark = handle.watermark.lock().expect("watermark mutex poisoned");
+        watermark.pending.extend(seqs.iter().copied());
+
+        let mut advanced = false;
+        loop {
+            let next = watermark.next;
+            if !watermark.pending.remove(&next) {
+                break;
+            }
+            watermark.next += 1;
+            advanced = true;
+        }
+
+        advanced.then_some(watermark.next)
+    };
+
+    if let Some(committed_offset) = committed_offset {
+        handle
+            .job_log
+            .lock()
+            .expect("job log mutex poisoned")
+            .advance_committed_offset(&handle.job_id, committed_offset)?;
+    }
 
     Ok(())
 }
@@ -158,55 +939,121 @@ async fn insert_hdfs_logs_batch(
 /// Process HDFS logs
 async fn process_hdfs_logs(args: Args) -> Result<()> {
     let infilename = if args.asset_dir.is_empty() {
-        PathBuf::from("hdfs-logs-multitenants.json")
+        PathBuf::from(asset_file_name(args.format))
     } else {
-        PathBuf::from(&args.asset_dir).join("hdfs-logs-multitenants.json")
+        PathBuf::from(&args.asset_dir).join(asset_file_name(args.format))
     };
 
-    println!(
-        "Processing logs from '{}' in batches of {}",
-        infilename.display(), args.batch_size
-    );
+    match &args.webhdfs_url {
+        Some(namenode_url) => println!(
+            "Processing logs from WebHDFS namenode '{}' (dir '{}') with {} workers, batches of {}",
+            namenode_url, args.asset_dir, args.workers, args.batch_size
+        ),
+        None => println!(
+            "Processing logs from '{}' with {} workers, batches of {}",
+            infilename.display(), args.workers, args.batch_size
+        ),
+    }
+
+    let pool = connect_to_database(&args).await?;
+    let mut setup_conn = pool
+        .get_conn()
+        .await
+        .context("Error getting a connection to set up the table")?;
+    if args.recreate {
+        recreate_hdfs_log_table(&mut setup_conn, &args.table_name).await?;
+    } else {
+        run_migrations(&mut setup_conn, &args.table_name).await?;
+    }
+    drop(setup_conn);
+
+    if args.migrate_only {
+        println!("Migrations complete, exiting (--migrate-only)");
+        return Ok(());
+    }
 
-    let mut conn = connect_to_database(&args.tidb_host, args.tidb_port).await?;
-    create_hdfs_log_table(&mut conn, &args.table_name).await?;
+    let job_log = if args.resume {
+        let job_id = args
+            .job_id
+            .clone()
+            .unwrap_or_else(|| infilename.display().to_string());
+        let job_log = JobLog::open(Path::new(&args.job_log_path))?;
+        if args.recreate {
+            // The data table was just dropped and recreated, so any progress recorded for
+            // this job id under the old table no longer corresponds to anything on disk.
+            job_log.reset_job(&job_id)?;
+        }
+        let resume_offset = job_log.start_or_resume(&job_id, &infilename.display().to_string())?;
+        if resume_offset > 0 {
+            println!(
+                "Resuming job '{}', skipping {} already-committed rows",
+                job_id, resume_offset
+            );
+        }
+        Some((
+            JobLogHandle {
+                job_log: Arc::new(Mutex::new(job_log)),
+                job_id,
+                watermark: Arc::new(Mutex::new(RowWatermark {
+                    next: resume_offset,
+                    pending: BTreeSet::new(),
+                })),
+            },
+            resume_offset,
+        ))
+    } else {
+        None
+    };
 
-    let log_iter = read_hdfs_logs(
-        infilename.to_str().context("Invalid file path")?,
-        args.max_rows,
-    )?;
+    let resume_offset = job_log.as_ref().map(|(_, offset)| *offset).unwrap_or(0);
+    let log_iter = build_log_iterator(&args, &infilename, resume_offset as usize)?;
+    let job_log_handle = job_log.map(|(handle, _)| handle);
 
-    let mut batch = Vec::with_capacity(args.batch_size);
+    let (sender, receiver) = crossbeam_channel::bounded::<(u64, LogEntry)>(args.channel_cap);
+    let producer = spawn_log_producer(log_iter, resume_offset, sender);
+
+    let mut worker_handles = Vec::with_capacity(args.workers);
+    for worker_id in 0..args.workers {
+        worker_handles.push(tokio::spawn(run_insert_worker(
+            worker_id,
+            pool.clone(),
+            args.table_name.clone(),
+            args.batch_size,
+            args.max_retries,
+            receiver.clone(),
+            job_log_handle.clone(),
+        )));
+    }
+    drop(receiver);
+
+    // Await every worker via `join_all` rather than bailing out of the loop on the first
+    // error, so a failing worker doesn't abort the others mid-batch: each worker still
+    // finishes its current batch and records it in the job log before we report the error.
     let mut total_inserted = 0;
-    let mut total_read = 0;
-
-    for log_result in log_iter {
-        match log_result {
-            Ok(log) => {
-                batch.push(log);
-                total_read += 1;
-
-                if batch.len() >= args.batch_size {
-                    insert_hdfs_logs_batch(&mut conn, &args.table_name, &batch).await?;
-                    total_inserted += batch.len();
-                    println!("Total logs inserted so far: {}", total_inserted);
-                    batch.clear();
-                }
-            }
-            Err(e) => {
-                eprintln!("Error processing log entry: {}", e);
+    let mut first_error = None;
+    for result in futures::future::join_all(worker_handles).await {
+        match result.context("Insert worker task panicked") {
+            Ok(Ok(rows_inserted)) => total_inserted += rows_inserted,
+            Ok(Err(e)) | Err(e) => {
+                first_error.get_or_insert(e);
             }
         }
     }
 
-    // Process remaining batch
-    if !batch.is_empty() {
-        insert_hdfs_logs_batch(&mut conn, &args.table_name, &batch).await?;
-        total_inserted += batch.len();
-        println!("Total logs inserted so far: {}", total_inserted);
+    let total_read = producer
+        .join()
+        .expect("Log producer thread panicked")?;
+
+    if let Some(e) = first_error {
+        return Err(e);
     }
 
-    println!("Read {} total log entries from {}", total_read, infilename.display());
+    println!(
+        "Read {} total log entries from {}, inserted {} total",
+        total_read,
+        infilename.display(),
+        total_inserted
+    );
 
     Ok(())
 }